@@ -0,0 +1,181 @@
+//! 可选的Discord Rich Presence子系统：搜索运行时通过本地Discord IPC socket
+//! 把进度（比如"Narrowing 512 -> 8 suspects"）展示在用户的Discord状态里。
+
+use discord_rich_presence::activity::{Activity, Assets, Timestamps};
+use discord_rich_presence::{DiscordIpc, DiscordIpcClient};
+use std::collections::HashSet;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 在 Discord 开发者后台为本项目申请的应用ID
+const DISCORD_CLIENT_ID: &str = "1234567890123456789";
+
+enum PresenceCommand {
+    Update { details: String, state: String },
+    Clear,
+}
+
+/// chunk0-3引入了可以并发运行的任务，Rich Presence的开关和展示的进度必须按JobId
+/// 区分：哪些任务选择了`rich_presence_enabled`（`enabled_jobs`），以及当前activity
+/// 展示的是哪个任务的进度（`owner`）。一个没有选择开启的任务结束时不应该影响其它
+/// 任务的展示，一个任务的进度更新也不该覆盖另一个任务正在展示的内容。
+#[derive(Default)]
+pub struct RichPresence {
+    enabled_jobs: Mutex<HashSet<String>>,
+    owner: Mutex<Option<String>>,
+    tx: Mutex<Option<Sender<PresenceCommand>>>,
+}
+
+impl RichPresence {
+    /// 某个任务开启了`rich_presence_enabled`并开始搜索时调用：把它记为"已启用"，
+    /// 如果当前没有任务在展示activity就把它设成owner，IPC线程还没起来的话就起一个
+    pub fn start(&self, job_id: &str) {
+        self.enabled_jobs.lock().unwrap().insert(job_id.to_string());
+
+        let mut owner = self.owner.lock().unwrap();
+        if owner.is_none() {
+            *owner = Some(job_id.to_string());
+        }
+        drop(owner);
+
+        let mut tx_guard = self.tx.lock().unwrap();
+        if tx_guard.is_none() {
+            let (tx, rx) = mpsc::channel();
+            thread::spawn(move || run_ipc_thread(rx));
+            *tx_guard = Some(tx);
+        }
+    }
+
+    /// 用最新的`SearchProgress`更新activity，只有当前的owner任务才能真正改写展示内容，
+    /// 这样多个任务同时运行时不会互相覆盖对方的进度
+    pub fn update_progress(&self, job_id: &str, remaining: u32, total: u32, message: &str) {
+        if self.owner.lock().unwrap().as_deref() != Some(job_id) {
+            return;
+        }
+        if let Some(tx) = self.tx.lock().unwrap().as_ref() {
+            let _ = tx.send(PresenceCommand::Update {
+                details: format!("Narrowing {} -> {} suspects", total, remaining),
+                state: message.to_string(),
+            });
+        }
+    }
+
+    /// 某个任务结束（正常完成或被`stop_search`取消）时调用。如果这个任务从未启用过
+    /// Rich Presence就什么也不做；如果它正是当前的owner，就把展示权交给另一个仍在
+    /// 启用中的任务，没有别的任务了才真正清空activity并关闭IPC连接
+    pub fn stop(&self, job_id: &str) {
+        let mut enabled_jobs = self.enabled_jobs.lock().unwrap();
+        if !enabled_jobs.remove(job_id) {
+            return;
+        }
+
+        let mut owner = self.owner.lock().unwrap();
+        if owner.as_deref() != Some(job_id) {
+            return;
+        }
+
+        *owner = enabled_jobs.iter().next().cloned();
+        if owner.is_none() {
+            if let Some(tx) = self.tx.lock().unwrap().take() {
+                let _ = tx.send(PresenceCommand::Clear);
+                // drop发送端后IPC线程会在收完Clear后自然退出
+            }
+        }
+        // 还有别的启用中的任务接手owner：activity会在它下一次Progress时自然更新，
+        // 这里不需要主动做什么
+    }
+}
+
+fn run_ipc_thread(rx: Receiver<PresenceCommand>) {
+    let mut client = match connect_with_retry() {
+        Some(client) => client,
+        None => return,
+    };
+
+    let start_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+
+    while let Ok(cmd) = rx.recv() {
+        match cmd {
+            PresenceCommand::Update { details, state } => {
+                let activity = Activity::new()
+                    .details(&details)
+                    .state(&state)
+                    .assets(Assets::new().large_image("tracker"))
+                    .timestamps(Timestamps::new().start(start_time));
+                let _ = client.set_activity(activity);
+            }
+            PresenceCommand::Clear => break,
+        }
+    }
+
+    let _ = client.clear_activity();
+    let _ = client.close();
+}
+
+/// Discord客户端可能还没启动，IPC socket暂时连不上时做几次重试
+fn connect_with_retry() -> Option<DiscordIpcClient> {
+    for _ in 0..5 {
+        match DiscordIpcClient::new(DISCORD_CLIENT_ID) {
+            Ok(mut client) => {
+                if client.connect().is_ok() {
+                    return Some(client);
+                }
+            }
+            Err(_) => {}
+        }
+        thread::sleep(Duration::from_secs(2));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 这几个测试直接摆弄`enabled_jobs`/`owner`这两个字段而不经过`start()`，因为
+    // `start()`会真的起一条尝试连接Discord IPC的线程，和这里要验证的"表驱动的owner
+    // 切换逻辑"没关系
+
+    /// chunk0-4修的bug：当前owner任务结束时，展示权应该交给另一个还启用着的任务
+    #[test]
+    fn owner_handoff_when_current_owner_stops() {
+        let presence = RichPresence::default();
+        presence.enabled_jobs.lock().unwrap().insert("job-a".to_string());
+        presence.enabled_jobs.lock().unwrap().insert("job-b".to_string());
+        *presence.owner.lock().unwrap() = Some("job-a".to_string());
+
+        presence.stop("job-a");
+
+        assert_eq!(presence.owner.lock().unwrap().as_deref(), Some("job-b"));
+        assert!(!presence.enabled_jobs.lock().unwrap().contains("job-a"));
+    }
+
+    /// 没有启用过Rich Presence的任务结束时不该影响当前owner的展示
+    #[test]
+    fn stopping_a_job_that_never_enabled_is_a_no_op() {
+        let presence = RichPresence::default();
+        presence.enabled_jobs.lock().unwrap().insert("job-a".to_string());
+        *presence.owner.lock().unwrap() = Some("job-a".to_string());
+
+        presence.stop("job-never-started");
+
+        assert_eq!(presence.owner.lock().unwrap().as_deref(), Some("job-a"));
+    }
+
+    /// 最后一个启用中的任务结束时owner应该清空，而不是留着一个已经失效的JobId
+    #[test]
+    fn owner_clears_when_no_enabled_jobs_remain() {
+        let presence = RichPresence::default();
+        presence.enabled_jobs.lock().unwrap().insert("job-a".to_string());
+        *presence.owner.lock().unwrap() = Some("job-a".to_string());
+
+        presence.stop("job-a");
+
+        assert!(presence.owner.lock().unwrap().is_none());
+    }
+}
@@ -1,69 +1,122 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod discord_presence;
+mod protocol;
+
+use discord_presence::RichPresence;
+use protocol::{Envelope, TrackerCommand, TrackerMessage};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::process::Stdio;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::api::process::{Command as SidecarCommand, CommandChild, CommandEvent};
 use tauri::Manager;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex as AsyncMutex;
+use uuid::Uuid;
 
-static RUNNING: AtomicBool = AtomicBool::new(false);
+/// 一次`start_binary_search`调用对应的任务标识，用于区分同时追踪多个`server_id`的搜索
+type JobId = String;
 
-/// 获取tracker可执行文件路径，处理Windows长路径前缀
-fn get_tracker_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
-    if cfg!(debug_assertions) {
-        // 开发模式：使用Python脚本
-        Ok(PathBuf::from("../python/tracker.py"))
-    } else {
-        // 生产模式：尝试多种路径
-        // 方式1: 使用 resolve_resource
-        if let Some(path) = app_handle.path_resolver().resolve_resource("tracker.exe") {
-            let path_str = path.to_string_lossy().to_string();
-            let clean_path = if path_str.starts_with("\\\\?\\") {
-                PathBuf::from(&path_str[4..])
-            } else {
-                path
-            };
-            if clean_path.exists() {
-                return Ok(clean_path);
-            }
+fn new_job_id() -> JobId {
+    Uuid::new_v4().to_string()
+}
+
+/// 生产模式下通过sidecar机制打包的tracker二进制的sidecar名（不含target-triple后缀，
+/// tauri-bundler在打包`tracker-<target-triple>`时会自动按平台挑选并去掉后缀）
+const TRACKER_SIDECAR: &str = "tracker";
+
+/// tracker的输出流。只被它自己对应的`run_search_job`任务读取，不与任何其它任务共享，
+/// 所以读下一行不需要加锁——读取阻塞时不会影响`stop_search`去拿控制端的锁
+enum TrackerReader {
+    Dev(Lines<BufReader<ChildStdout>>),
+    Sidecar(tauri::async_runtime::Receiver<CommandEvent>),
+}
+
+impl TrackerReader {
+    async fn next_line(&mut self) -> Option<String> {
+        match self {
+            TrackerReader::Dev(lines) => lines.next_line().await.ok().flatten(),
+            TrackerReader::Sidecar(rx) => loop {
+                match rx.recv().await? {
+                    CommandEvent::Stdout(line) => return Some(line),
+                    _ => continue,
+                }
+            },
         }
+    }
+}
+
+/// tracker的控制端：写stdin命令、杀进程。被`run_search_job`的收尾清理和`stop_search`
+/// 共享，单独用一个锁，绝不会和（可能长时间阻塞的）读取端共享同一把锁——这样即便tracker
+/// 卡死不产生任何输出，`stop_search`的优雅取消/硬杀仍然能立刻拿到锁执行
+enum TrackerController {
+    Dev(Child, ChildStdin),
+    Sidecar(CommandChild),
+}
 
-        // 方式2: 使用 resource_dir
-        if let Some(resource_dir) = app_handle.path_resolver().resource_dir() {
-            let path = resource_dir.join("tracker.exe");
-            let path_str = path.to_string_lossy().to_string();
-            let clean_path = if path_str.starts_with("\\\\?\\") {
-                PathBuf::from(&path_str[4..])
-            } else {
-                path
-            };
-            if clean_path.exists() {
-                return Ok(clean_path);
+impl TrackerController {
+    /// 把一条命令写进tracker的stdin，用来请求优雅取消/暂停/恢复
+    async fn send_command(&mut self, cmd: &TrackerCommand) -> Result<(), String> {
+        let line = protocol::encode_command(cmd)?;
+        match self {
+            TrackerController::Dev(_, stdin) => stdin
+                .write_all(line.as_bytes())
+                .await
+                .map_err(|e| e.to_string()),
+            TrackerController::Sidecar(child) => {
+                child.write(line.as_bytes()).map_err(|e| e.to_string())
             }
         }
+    }
 
-        // 方式3: 使用 exe 所在目录
-        if let Ok(exe_path) = std::env::current_exe() {
-            if let Some(exe_dir) = exe_path.parent() {
-                // 尝试 exe_dir/resources/tracker.exe
-                let path = exe_dir.join("resources").join("tracker.exe");
-                if path.exists() {
-                    return Ok(path);
+    async fn kill(self) -> Result<(), String> {
+        match self {
+            TrackerController::Dev(mut child, _) => {
+                // Windows上tokio的kill只杀掉脚本本身，卡住的子进程（比如挂起的Discord HTTP请求）
+                // 可能还留在进程树里，所以额外用taskkill /T把整棵树都带走
+                #[cfg(windows)]
+                if let Some(pid) = child.id() {
+                    let _ = Command::new("taskkill")
+                        .args(["/PID", &pid.to_string(), "/T", "/F"])
+                        .output()
+                        .await;
                 }
-                // 尝试 exe_dir/tracker.exe
-                let path = exe_dir.join("tracker.exe");
-                if path.exists() {
-                    return Ok(path);
+
+                child.start_kill().map_err(|e| format!("终止tracker失败: {}", e))?;
+                let _ = child.wait().await;
+                Ok(())
+            }
+            TrackerController::Sidecar(child) => {
+                // 和Dev分支一样：Windows上卡住的子进程（比如挂起的Discord HTTP请求）
+                // 可能不会被child.kill()一起带走，所以同样用taskkill /T把整棵树都杀掉，
+                // 不能指望sidecar自己处理这件事
+                #[cfg(windows)]
+                {
+                    let pid = child.pid();
+                    let _ = Command::new("taskkill")
+                        .args(["/PID", &pid.to_string(), "/T", "/F"])
+                        .output()
+                        .await;
                 }
+
+                child.kill().map_err(|e| format!("终止tracker失败: {}", e))
             }
         }
-
-        Err("找不到tracker.exe，请确保程序完整安装".to_string())
     }
 }
 
+/// 某个任务持有的控制端槽位，取出(take)之后代表该任务已被`stop_search`终止
+type JobSlot = Arc<AsyncMutex<Option<TrackerController>>>;
+
+/// 所有正在运行的tracker子进程，按JobId索引，允许同时追踪多个`server_id`的搜索。
+/// 外层是同步Mutex只做短暂的查表/增删，每个任务的控制端（写stdin、杀进程）用各自的
+/// 异步Mutex；读stdout不经过这些锁，所以一个任务阻塞在读stdout上不会卡住任何`stop_search`
+#[derive(Default)]
+struct SearchState(Mutex<HashMap<JobId, JobSlot>>);
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct Config {
@@ -85,6 +138,8 @@ struct Config {
     proxy_host: String,
     #[serde(default = "default_proxy_port")]
     proxy_port: u16,
+    #[serde(default)]
+    rich_presence_enabled: bool,
 }
 
 fn default_proxy_host() -> String {
@@ -116,73 +171,213 @@ struct SearchProgress {
     names: Vec<String>,
 }
 
+/// `search-progress`事件负载，带上JobId让前端知道是哪个搜索在更新
+#[derive(Debug, Serialize, Clone)]
+struct ProgressEvent {
+    job_id: JobId,
+    #[serde(flatten)]
+    progress: SearchProgress,
+}
+
+/// `search-result`事件负载，一个搜索结束（找到结果或被取消）时发出
+#[derive(Debug, Serialize, Clone)]
+struct ResultEvent {
+    job_id: JobId,
+    leaker: Option<LeakerInfo>,
+}
+
+/// `search-error`事件负载：tracker侧报出的结构化错误，或者帧协议解析失败
+#[derive(Debug, Serialize, Clone)]
+struct ErrorEvent {
+    job_id: JobId,
+    message: String,
+}
+
+/// `search-log`事件负载：tracker的`Log`消息，透传给前端方便调试
+#[derive(Debug, Serialize, Clone)]
+struct LogEvent {
+    job_id: JobId,
+    message: String,
+}
+
+/// 立即分配一个JobId并返回，真正的搜索在后台任务里跑，这样多个`server_id`可以同时追踪
 #[tauri::command]
 async fn start_binary_search(
     config: Config,
     app_handle: tauri::AppHandle,
-) -> Result<Option<LeakerInfo>, String> {
-    if RUNNING.load(Ordering::SeqCst) {
-        return Err("搜索已在运行中".to_string());
+    state: tauri::State<'_, SearchState>,
+    rich_presence: tauri::State<'_, RichPresence>,
+) -> Result<JobId, String> {
+    let job_id = new_job_id();
+    let config_json = serde_json::to_string(&config).map_err(|e| e.to_string())?;
+
+    if config.rich_presence_enabled {
+        rich_presence.start(&job_id);
     }
 
-    RUNNING.store(true, Ordering::SeqCst);
-
-    let config_json = serde_json::to_string(&config)
-        .map_err(|e| e.to_string())?;
-
-    let tracker_path = get_tracker_path(&app_handle)?;
-
-    // 根据模式选择命令
-    let mut child = if cfg!(debug_assertions) {
-        // 开发模式：使用 python 运行脚本
-        Command::new("python")
-            .arg(&tracker_path)
+    let (controller, reader) = if cfg!(debug_assertions) {
+        // 开发模式：继续用 python 运行脚本，不走sidecar
+        let mut child = Command::new("python")
+            .arg("../python/tracker.py")
             .arg("--config")
             .arg(&config_json)
+            .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
-            .map_err(|e| format!("启动Python失败: {}", e))?
+            .map_err(|e| format!("启动Python失败: {}", e))?;
+        let stdout = child.stdout.take().ok_or("无法获取stdout")?;
+        let stdin = child.stdin.take().ok_or("无法获取stdin")?;
+        (
+            TrackerController::Dev(child, stdin),
+            TrackerReader::Dev(BufReader::new(stdout).lines()),
+        )
     } else {
-        // 生产模式：直接运行 exe
-        Command::new(&tracker_path)
-            .arg("--config")
-            .arg(&config_json)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
+        // 生产模式：通过Tauri sidecar运行，按target-triple自动挑选平台对应的tracker二进制，
+        // 不再需要手工猜exe路径
+        let (rx, child) = SidecarCommand::new_sidecar(TRACKER_SIDECAR)
+            .map_err(|e| format!("找不到tracker sidecar: {}", e))?
+            .args(["--config", &config_json])
             .spawn()
-            .map_err(|e| format!("启动tracker失败: {}", e))?
+            .map_err(|e| format!("启动tracker失败: {}", e))?;
+        (TrackerController::Sidecar(child), TrackerReader::Sidecar(rx))
     };
 
-    let stdout = child.stdout.take()
-        .ok_or("无法获取stdout")?;
+    let slot: JobSlot = Arc::new(AsyncMutex::new(Some(controller)));
+    state
+        .0
+        .lock()
+        .map_err(|e| e.to_string())?
+        .insert(job_id.clone(), slot.clone());
+
+    // 真正的读取循环放到独立任务里跑，命令本身立即把JobId还给前端。slot已经存进了
+    // state的表里，run_search_job不需要再持有一份——它只管读reader、收尾时把自己
+    // 从表里摘掉，控制端的读写全部交给stop_search通过表里的slot去做
+    let task_job_id = job_id.clone();
+    tokio::spawn(async move {
+        run_search_job(task_job_id, app_handle, reader).await;
+    });
+
+    Ok(job_id)
+}
 
-    let mut reader = BufReader::new(stdout).lines();
+async fn run_search_job(job_id: JobId, app_handle: tauri::AppHandle, mut reader: TrackerReader) {
     let mut result: Option<LeakerInfo> = None;
 
-    while let Ok(Some(line)) = reader.next_line().await {
-        if !RUNNING.load(Ordering::SeqCst) {
-            break;
-        }
+    // reader只属于这个任务，next_line()阻塞期间不持有slot的锁，stop_search可以随时
+    // 拿到控制端去写入取消命令或者硬杀
+    while let Some(line) = reader.next_line().await {
+        let envelope = match Envelope::parse(&line) {
+            Ok(envelope) => envelope,
+            Err(message) => {
+                // 解析失败或者协议版本对不上，都作为结构化错误报出来，而不是像以前
+                // 那样碰到无法识别的行就直接悄悄丢弃
+                let _ = app_handle.emit_all(
+                    "search-error",
+                    ErrorEvent { job_id: job_id.clone(), message },
+                );
+                continue;
+            }
+        };
 
-        if line.starts_with("PROGRESS:") {
-            if let Ok(progress) = serde_json::from_str::<SearchProgress>(&line[9..]) {
-                let _ = app_handle.emit_all("search-progress", progress);
+        match envelope.message {
+            TrackerMessage::Progress(progress) => {
+                if let Some(rich_presence) = app_handle.try_state::<RichPresence>() {
+                    rich_presence.update_progress(&job_id, progress.remaining, progress.total, &progress.message);
+                }
+                let _ = app_handle.emit_all(
+                    "search-progress",
+                    ProgressEvent { job_id: job_id.clone(), progress },
+                );
             }
-        } else if line.starts_with("RESULT:") {
-            if let Ok(leaker) = serde_json::from_str::<LeakerInfo>(&line[7..]) {
+            TrackerMessage::Result(leaker) => {
                 result = Some(leaker);
             }
+            TrackerMessage::Error { message } => {
+                let _ = app_handle.emit_all(
+                    "search-error",
+                    ErrorEvent { job_id: job_id.clone(), message },
+                );
+            }
+            TrackerMessage::Log { message } => {
+                let _ = app_handle.emit_all(
+                    "search-log",
+                    LogEvent { job_id: job_id.clone(), message },
+                );
+            }
+            // Connected只在test_connection那条独立的探测请求里有意义；Heartbeat只是让
+            // 连接保持活跃，两者在正常搜索过程中都不需要做什么
+            TrackerMessage::Connected { .. } | TrackerMessage::Heartbeat => {}
         }
     }
 
-    RUNNING.store(false, Ordering::SeqCst);
-    Ok(result)
+    if let Some(state) = app_handle.try_state::<SearchState>() {
+        if let Ok(mut guard) = state.0.lock() {
+            guard.remove(&job_id);
+        }
+    }
+    if let Some(rich_presence) = app_handle.try_state::<RichPresence>() {
+        rich_presence.stop(&job_id);
+    }
+    let _ = app_handle.emit_all("search-result", ResultEvent { job_id, leaker: result });
 }
 
+/// 给tracker一个优雅退出的机会，超时了才硬杀
+const GRACEFUL_CANCEL_TIMEOUT: Duration = Duration::from_secs(3);
+
 #[tauri::command]
-fn stop_search() -> Result<(), String> {
-    RUNNING.store(false, Ordering::SeqCst);
+async fn stop_search(
+    job_id: JobId,
+    state: tauri::State<'_, SearchState>,
+    rich_presence: tauri::State<'_, RichPresence>,
+) -> Result<(), String> {
+    let slot = {
+        let guard = state.0.lock().map_err(|e| e.to_string())?;
+        guard
+            .get(&job_id)
+            .cloned()
+            .ok_or_else(|| format!("没有找到正在运行的搜索: {}", job_id))?
+    };
+
+    // 先请求优雅取消，让tracker有机会把已经确认的LeakerInfo flush出来；
+    // 这段时间run_search_job还在正常读取它的输出、正常emit事件
+    {
+        let mut guard = slot.lock().await;
+        if let Some(child) = guard.as_mut() {
+            let _ = child.send_command(&TrackerCommand::Cancel).await;
+        }
+    }
+
+    let exited_gracefully = tokio::time::timeout(GRACEFUL_CANCEL_TIMEOUT, async {
+        loop {
+            let still_running = state
+                .0
+                .lock()
+                .map(|guard| guard.contains_key(&job_id))
+                .unwrap_or(false);
+            if !still_running {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .is_ok();
+
+    if !exited_gracefully {
+        // 优雅取消超时，直接硬杀
+        let child = {
+            let mut guard = state.0.lock().map_err(|e| e.to_string())?;
+            guard.remove(&job_id)
+        };
+        if let Some(slot) = child {
+            if let Some(child) = slot.lock().await.take() {
+                child.kill().await?;
+            }
+        }
+    }
+
+    rich_presence.stop(&job_id);
     Ok(())
 }
 
@@ -192,15 +387,12 @@ async fn test_connection(
     proxyEnabled: bool,
     proxyHost: String,
     proxyPort: u16,
-    app_handle: tauri::AppHandle
+    _app_handle: tauri::AppHandle,
 ) -> Result<String, String> {
-    let tracker_path = get_tracker_path(&app_handle)?;
-
-    // 根据模式选择命令
-    let output = if cfg!(debug_assertions) {
+    let (stdout, stderr) = if cfg!(debug_assertions) {
         // 开发模式：使用 python 运行脚本
         let mut cmd = Command::new("python");
-        cmd.arg(&tracker_path)
+        cmd.arg("../python/tracker.py")
             .arg("--test-connection")
             .arg(&token);
 
@@ -209,45 +401,51 @@ async fn test_connection(
                 .arg(format!("{}:{}", proxyHost, proxyPort));
         }
 
-        cmd.output()
-            .await
-            .map_err(|e| format!("启动Python失败: {}", e))?
+        let output = cmd.output().await.map_err(|e| format!("启动Python失败: {}", e))?;
+        (
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )
     } else {
-        // 生产模式：直接运行 exe
-        let mut cmd = Command::new(&tracker_path);
-        cmd.arg("--test-connection")
-            .arg(&token);
+        // 生产模式：通过sidecar运行，同一套代码在各平台上都能找到对应的tracker二进制。
+        // sidecar的`output()`是同步阻塞调用，不能直接在async fn里await它所在的线程，
+        // 否则会一直占着tokio的工作线程，所以丢进spawn_blocking里单独跑
+        let mut cmd = SidecarCommand::new_sidecar(TRACKER_SIDECAR)
+            .map_err(|e| format!("找不到tracker sidecar: {}", e))?
+            .args(["--test-connection", &token]);
 
         if proxyEnabled {
-            cmd.arg("--proxy")
-                .arg(format!("{}:{}", proxyHost, proxyPort));
+            cmd = cmd.args(["--proxy", &format!("{}:{}", proxyHost, proxyPort)]);
         }
 
-        cmd.output()
+        let output = tauri::async_runtime::spawn_blocking(move || cmd.output())
             .await
-            .map_err(|e| format!("启动tracker失败: {}", e))?
+            .map_err(|e| format!("执行tracker任务失败: {}", e))?
+            .map_err(|e| format!("启动tracker失败: {}", e))?;
+        (output.stdout, output.stderr)
     };
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-
-    // 查找包含 CONNECTED: 的行
+    // 找一条解析成功且类型是Connected的消息
     for line in stdout.lines() {
-        if line.starts_with("CONNECTED:") {
-            return Ok(line[10..].trim().to_string());
+        if let Ok(envelope) = Envelope::parse(line) {
+            if let TrackerMessage::Connected { info } = envelope.message {
+                return Ok(info);
+            }
         }
     }
 
-    // 没找到 CONNECTED，返回错误
-    let stderr = String::from_utf8_lossy(&output.stderr);
+    // 没找到 Connected 消息，返回错误
     if stderr.is_empty() {
         Err(format!("连接失败，stdout: {}", stdout))
     } else {
-        Err(stderr.to_string())
+        Err(stderr)
     }
 }
 
 fn main() {
     tauri::Builder::default()
+        .manage(SearchState::default())
+        .manage(RichPresence::default())
         .invoke_handler(tauri::generate_handler![
             start_binary_search,
             stop_search,
@@ -256,3 +454,63 @@ fn main() {
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Stdio as StdStdio;
+
+    /// 起一个老老实实等在stdin上的python子进程，充当测试里的"tracker"——不需要真的
+    /// 实现协议，只要stdin/stdout还在就足够验证两个任务的控制端是否互相独立
+    fn spawn_dummy_child() -> (Child, ChildStdin) {
+        let mut child = Command::new("python")
+            .args(["-c", "import sys; [sys.stdin.readline() for _ in iter(int, 1)]"])
+            .stdin(StdStdio::piped())
+            .stdout(StdStdio::piped())
+            .spawn()
+            .expect("spawn python for test");
+        let stdin = child.stdin.take().expect("stdin");
+        (child, stdin)
+    }
+
+    /// chunk0-3修的bug：一个任务被`stop_search`硬杀时绝不能影响到另一个还在跑的任务——
+    /// 两个任务的JobSlot各自有自己的锁，表里摘掉一个条目不该动到另一个的控制端
+    #[tokio::test]
+    async fn stopping_one_job_does_not_affect_another() {
+        let state = SearchState::default();
+        let (child_a, stdin_a) = spawn_dummy_child();
+        let (child_b, stdin_b) = spawn_dummy_child();
+
+        let slot_a: JobSlot = Arc::new(AsyncMutex::new(Some(TrackerController::Dev(child_a, stdin_a))));
+        let slot_b: JobSlot = Arc::new(AsyncMutex::new(Some(TrackerController::Dev(child_b, stdin_b))));
+
+        state.0.lock().unwrap().insert("job-a".to_string(), slot_a);
+        state.0.lock().unwrap().insert("job-b".to_string(), slot_b.clone());
+
+        // 模拟stop_search的硬杀路径：从表里摘掉job-a、take出它的控制端、杀掉
+        let taken = state.0.lock().unwrap().remove("job-a");
+        assert!(taken.is_some());
+        let controller = taken
+            .unwrap()
+            .lock()
+            .await
+            .take()
+            .expect("job-a should still have its controller");
+        controller.kill().await.expect("killing job-a should succeed");
+
+        // job-b完全没受影响：还在表里，控制端还能正常发命令
+        assert!(state.0.lock().unwrap().contains_key("job-b"));
+        let mut guard = slot_b.lock().await;
+        let controller = guard.as_mut().expect("job-b's controller should be untouched");
+        controller
+            .send_command(&TrackerCommand::Cancel)
+            .await
+            .expect("job-b should still accept commands after job-a is killed");
+        drop(guard);
+
+        // 不要在测试进程里留下孤儿python
+        if let Some(controller) = slot_b.lock().await.take() {
+            let _ = controller.kill().await;
+        }
+    }
+}
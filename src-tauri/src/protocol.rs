@@ -0,0 +1,118 @@
+//! 小型的、带版本号的帧协议：tracker把每条消息编码成一行JSON写到stdout，
+//! 形如`{"type": "...", "payload": {...}}`，我们按同样的方式把命令写到它的stdin。
+//! 比起历史上的`PROGRESS:`/`RESULT:`/`CONNECTED:`行前缀，这样解析不出来的内容会变成
+//! 一个结构化的错误而不是被静默丢弃，版本不匹配时也能立刻报错而不是假装兼容。
+
+use crate::{LeakerInfo, SearchProgress};
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+fn default_version() -> u32 {
+    PROTOCOL_VERSION
+}
+
+/// tracker -> app 的消息。字段名和`type`取值都用snake_case，方便Python那边原样照抄
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum TrackerMessage {
+    Progress(SearchProgress),
+    Result(LeakerInfo),
+    Connected { info: String },
+    Error { message: String },
+    Log { message: String },
+    Heartbeat,
+}
+
+/// app -> tracker 的命令
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum TrackerCommand {
+    Cancel,
+    Pause,
+    Resume,
+}
+
+/// 一行完整的JSON消息，额外带着协议版本号
+#[derive(Debug, Deserialize)]
+pub struct Envelope {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(flatten)]
+    pub message: TrackerMessage,
+}
+
+impl Envelope {
+    /// 解析一行tracker输出。版本号不匹配时直接报错，而不是尝试兼容着解析下去
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let envelope: Envelope = serde_json::from_str(line)
+            .map_err(|e| format!("无法解析tracker消息: {} (原始内容: {})", e, line))?;
+
+        if envelope.version != PROTOCOL_VERSION {
+            return Err(format!(
+                "tracker协议版本不兼容: 本程序是v{}, tracker是v{}",
+                PROTOCOL_VERSION, envelope.version
+            ));
+        }
+
+        Ok(envelope)
+    }
+}
+
+/// 把一条命令编码成一行（含结尾换行符）JSON，直接写到tracker的stdin
+pub fn encode_command(cmd: &TrackerCommand) -> Result<String, String> {
+    let mut line = serde_json::to_string(cmd).map_err(|e| e.to_string())?;
+    line.push('\n');
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_valid_progress_envelope() {
+        let line = r#"{"version":1,"type":"progress","payload":{"step":2,"total":512,"remaining":128,"message":"narrowing","names":["a","b"]}}"#;
+        let envelope = Envelope::parse(line).expect("should parse");
+        match envelope.message {
+            TrackerMessage::Progress(progress) => {
+                assert_eq!(progress.step, 2);
+                assert_eq!(progress.total, 512);
+                assert_eq!(progress.remaining, 128);
+                assert_eq!(progress.message, "narrowing");
+                assert_eq!(progress.names, vec!["a".to_string(), "b".to_string()]);
+            }
+            other => panic!("expected Progress, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn missing_version_defaults_and_succeeds() {
+        let line = r#"{"type":"heartbeat"}"#;
+        let envelope = Envelope::parse(line).expect("missing version should default, not fail");
+        assert_eq!(envelope.version, PROTOCOL_VERSION);
+        assert!(matches!(envelope.message, TrackerMessage::Heartbeat));
+    }
+
+    #[test]
+    fn mismatched_version_is_rejected() {
+        let line = r#"{"version":99,"type":"heartbeat"}"#;
+        let err = Envelope::parse(line).expect_err("version mismatch should fail loudly");
+        assert!(err.contains("版本不兼容"));
+    }
+
+    #[test]
+    fn malformed_json_is_rejected() {
+        let err = Envelope::parse("not json at all").expect_err("garbage input should fail");
+        assert!(err.contains("无法解析tracker消息"));
+    }
+
+    #[test]
+    fn encode_command_round_trips_through_parse_shaped_json() {
+        let line = encode_command(&TrackerCommand::Cancel).expect("should encode");
+        assert!(line.ends_with('\n'));
+        let value: serde_json::Value =
+            serde_json::from_str(line.trim_end()).expect("encoded command should be valid JSON");
+        assert_eq!(value["type"], "cancel");
+    }
+}